@@ -0,0 +1,190 @@
+use crate::mesh::{Mesh, Vec3};
+use indicatif::ProgressBar;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Output mesh format, selectable via `--format` or inferred from the
+/// output file's extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    StlBinary,
+    StlAscii,
+    Obj,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "stl-bin" => Some(Format::StlBinary),
+            "stl-ascii" => Some(Format::StlAscii),
+            "obj" => Some(Format::Obj),
+            _ => None,
+        }
+    }
+
+    /// Infers the format from an output path's extension, defaulting to
+    /// binary STL when the extension is unknown or absent.
+    pub fn infer(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("obj") => Format::Obj,
+            _ => Format::StlBinary,
+        }
+    }
+}
+
+/// Writes a `Mesh` out to disk in a particular file format.
+pub trait MeshExporter {
+    fn export(&self, mesh: Mesh, output_path: &Path) -> io::Result<()>;
+}
+
+pub fn exporter_for(format: Format) -> Box<dyn MeshExporter> {
+    match format {
+        Format::StlBinary => Box::new(StlBinaryExporter),
+        Format::StlAscii => Box::new(StlAsciiExporter),
+        Format::Obj => Box::new(ObjExporter),
+    }
+}
+
+pub struct StlBinaryExporter;
+
+impl MeshExporter for StlBinaryExporter {
+    fn export(&self, mesh: Mesh, output_path: &Path) -> io::Result<()> {
+        let mut w = File::create(output_path)?;
+
+        // Write 80 byte header
+        w.write_all(&[0; 80])?;
+        // Write number of triangles (u32)
+        w.write_all(&(mesh.len() as u32).to_le_bytes())?;
+
+        println!("Writing binary STL...");
+        let bar = ProgressBar::new(mesh.len() as u64);
+
+        for t in mesh {
+            w.write_all(&t.normal.to_le_bytes())?;
+            w.write_all(&t.v0.to_le_bytes())?;
+            w.write_all(&t.v1.to_le_bytes())?;
+            w.write_all(&t.v2.to_le_bytes())?;
+            // Write attribute byte count (u16)
+            w.write_all(&[0, 0])?;
+            bar.inc(1);
+        }
+
+        Ok(())
+    }
+}
+
+pub struct StlAsciiExporter;
+
+impl MeshExporter for StlAsciiExporter {
+    fn export(&self, mesh: Mesh, output_path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(output_path)?);
+
+        println!("Writing ASCII STL...");
+        let bar = ProgressBar::new(mesh.len() as u64);
+
+        writeln!(w, "solid lithophoto")?;
+        for t in &mesh {
+            writeln!(
+                w,
+                "facet normal {} {} {}",
+                t.normal.x, t.normal.y, t.normal.z
+            )?;
+            writeln!(w, "outer loop")?;
+            for v in [&t.v0, &t.v1, &t.v2] {
+                writeln!(w, "vertex {} {} {}", v.x, v.y, v.z)?;
+            }
+            writeln!(w, "endloop")?;
+            writeln!(w, "endfacet")?;
+            bar.inc(1);
+        }
+        writeln!(w, "endsolid lithophoto")?;
+
+        Ok(())
+    }
+}
+
+/// Quantizes a coordinate to a hashable key so near-identical vertices
+/// (e.g. shared corners produced by neighbouring quads) dedupe into a
+/// single entry in the vertex table.
+fn quantize(v: &Vec3) -> (i64, i64, i64) {
+    const SCALE: f32 = 1e4;
+    (
+        (v.x * SCALE).round() as i64,
+        (v.y * SCALE).round() as i64,
+        (v.z * SCALE).round() as i64,
+    )
+}
+
+pub struct ObjExporter;
+
+impl MeshExporter for ObjExporter {
+    fn export(&self, mesh: Mesh, output_path: &Path) -> io::Result<()> {
+        let mtl_name = output_path
+            .file_stem()
+            .map(|s| format!("{}.mtl", s.to_string_lossy()))
+            .unwrap_or_else(|| "lithophoto.mtl".to_string());
+
+        println!("Deduplicating vertices...");
+        let mut indices: HashMap<(i64, i64, i64), u32> = HashMap::new();
+        let mut vertices: Vec<Vec3> = Vec::new();
+        let mut faces: Vec<[u32; 3]> = Vec::with_capacity(mesh.len());
+
+        let mut vertex_index = |v: &Vec3| -> u32 {
+            let key = quantize(v);
+            *indices.entry(key).or_insert_with(|| {
+                vertices.push(*v);
+                (vertices.len() - 1) as u32
+            })
+        };
+
+        let bar = ProgressBar::new(mesh.len() as u64);
+        for t in &mesh {
+            let i0 = vertex_index(&t.v0);
+            let i1 = vertex_index(&t.v1);
+            let i2 = vertex_index(&t.v2);
+            faces.push([i0, i1, i2]);
+            bar.inc(1);
+        }
+
+        println!(
+            "Writing indexed OBJ ({} vertices, {} faces, deduplicated from {} triangles)...",
+            vertices.len(),
+            faces.len(),
+            mesh.len()
+        );
+        let mut w = BufWriter::new(File::create(output_path)?);
+
+        writeln!(w, "mtllib {}", mtl_name)?;
+        writeln!(w, "usemtl lithophane")?;
+
+        for v in &vertices {
+            writeln!(w, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        for f in &faces {
+            // OBJ vertex indices are 1-based
+            writeln!(w, "f {} {} {}", f[0] + 1, f[1] + 1, f[2] + 1)?;
+        }
+
+        let mtl_path = output_path.with_file_name(&mtl_name);
+        write_mtl(&mtl_path)?;
+
+        Ok(())
+    }
+}
+
+/// Writes a companion `.mtl` describing a translucent material suitable
+/// for a backlit lithophane, loadable by any OBJ-consuming renderer or
+/// slicer.
+fn write_mtl(mtl_path: &Path) -> io::Result<()> {
+    let mut w = File::create(mtl_path)?;
+    writeln!(w, "newmtl lithophane")?;
+    writeln!(w, "Ka 0.1 0.1 0.1")?;
+    writeln!(w, "Kd 0.9 0.9 0.9")?;
+    writeln!(w, "Ks 0.1 0.1 0.1")?;
+    writeln!(w, "Ns 10.0")?;
+    writeln!(w, "d 0.5")?;
+    writeln!(w, "illum 9")?;
+    Ok(())
+}
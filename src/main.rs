@@ -1,290 +1,21 @@
-use clap::{App, Arg};
-use image::{DynamicImage, GenericImageView};
-use indicatif::ProgressBar;
-use indicatif::ProgressIterator;
-use std::fs::File;
-
-struct Vec3 {
-    x: f32,
-    y: f32,
-    z: f32,
-}
-
-impl Vec3 {
-    fn to_le_bytes(&self) -> [u8; 12] {
-        let mut bytes = [0; 12];
-        bytes[0..4].copy_from_slice(&self.x.to_le_bytes());
-        bytes[4..8].copy_from_slice(&self.y.to_le_bytes());
-        bytes[8..12].copy_from_slice(&self.z.to_le_bytes());
-        bytes
-    }
-}
-
-impl Copy for Vec3 {}
-
-impl Clone for Vec3 {
-    fn clone(&self) -> Self {
-        Vec3 {
-            x: self.x,
-            y: self.y,
-            z: self.z,
-        }
-    }
-}
-
-struct Triangle {
-    normal: Vec3,
-    v0: Vec3,
-    v1: Vec3,
-    v2: Vec3,
-}
-
-type Mesh = Vec<Triangle>;
-
-fn generate_stl_mesh<T: std::io::Write>(m: Mesh, w: &mut T) {
-    // Write 80 byte header
-    for _ in 0..80 {
-        w.write(&[0]).unwrap();
-    }
-    // Write number of triangles (u32)
-    let num_triangles = m.len() as u32;
-    let num_triangles_bytes = num_triangles.to_le_bytes();
-    w.write(&num_triangles_bytes).unwrap();
-
-    println!("Writing STL...");
-    let bar = ProgressBar::new(num_triangles as u64);
-
-    // Write triangles
-    for t in m {
-        let normal = t.normal;
-        let v0 = t.v0;
-        let v1 = t.v1;
-        let v2 = t.v2;
-
-        let normal_bytes = normal.to_le_bytes();
-        w.write(&normal_bytes).unwrap();
-
-        let v0_bytes = v0.to_le_bytes();
-        w.write(&v0_bytes).unwrap();
-
-        let v1_bytes = v1.to_le_bytes();
-        w.write(&v1_bytes).unwrap();
-
-        let v2_bytes = v2.to_le_bytes();
-        w.write(&v2_bytes).unwrap();
-
-        // Write attribute byte count (u16)
-        w.write(&[0, 0]).unwrap();
-
-        bar.inc(1);
-    }
-}
-
-fn get_pixel_brightness(r: u8, g: u8, b: u8) -> f32 {
-    // Use the standard formula for brightness
-    let brightness = (r as f32 * 0.299) + (g as f32 * 0.587) + (b as f32 * 0.114);
-    brightness / 255.0
-}
-
-fn image_to_mesh(img: &DynamicImage, mesh_width: f32, mesh_thickness: f32, contrast: f32) -> Mesh {
-    let mut mesh = Mesh::new();
-
-    let (width, height) = img.dimensions();
-
-    let brightness_to_mm =
-        |brightness: f32| mesh_thickness - (brightness * contrast * mesh_thickness);
-
-    let pixel_coord_to_mm = |val| val as f32 * mesh_width / (width as f32);
-    let get_thickness_vec3 = |x, y| {
-        let pixel = img.get_pixel(x, height - y - 1);
-        let brightness = get_pixel_brightness(pixel[0], pixel[1], pixel[2]);
-        let mm = brightness_to_mm(brightness);
-        Vec3 {
-            x: pixel_coord_to_mm(x),
-            y: pixel_coord_to_mm(y),
-            z: mm,
-        }
-    };
-    println!("Computing brightness...");
-    let thickness = (0..height)
-        .progress()
-        .map(|y| {
-            (0..width)
-                .map(|x| get_thickness_vec3(x, y))
-                .collect::<Vec<_>>()
-        })
-        .collect::<Vec<_>>();
-    println!("Generating mesh...");
-
-    let normal = |v0: Vec3, v1: Vec3, v2: Vec3| Vec3 {
-        x: (v1.y - v0.y) * (v2.z - v0.z) - (v1.z - v0.z) * (v2.y - v0.y),
-        y: (v1.z - v0.z) * (v2.x - v0.x) - (v1.x - v0.x) * (v2.z - v0.z),
-        z: (v1.x - v0.x) * (v2.y - v0.y) - (v1.y - v0.y) * (v2.x - v0.x),
-    };
-
-    let add_quad = |mesh: &mut Mesh, v0: Vec3, v1: Vec3, v2: Vec3, v3: Vec3| {
-        let normal = normal(v0, v1, v2);
-        mesh.push(Triangle { normal, v0, v1, v2 });
-        mesh.push(Triangle {
-            normal,
-            v0,
-            v1: v2,
-            v2: v3,
-        });
-    };
-
-    let add_quad_with_normal =
-        |mesh: &mut Mesh, v0: Vec3, v1: Vec3, v2: Vec3, v3: Vec3, n: Vec3| {
-            mesh.push(Triangle {
-                normal: n,
-                v0,
-                v1,
-                v2,
-            });
-            mesh.push(Triangle {
-                normal: n,
-                v0,
-                v1: v2,
-                v2: v3,
-            });
-        };
-
-    // Create front face by tesselating a plane with the given thickness
-    for y in (0..height - 1).progress() {
-        for x in 0..width - 1 {
-            let (x, y) = (x as usize, y as usize);
-            let v0 = thickness[y][x];
-            let v1 = thickness[y][x + 1];
-            let v2 = thickness[y + 1][x + 1];
-            let v3 = thickness[y + 1][x];
-
-            add_quad(&mut mesh, v0, v1, v2, v3);
-        }
-    }
-
-    // Create back face
-    add_quad_with_normal(
-        &mut mesh,
-        Vec3 {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        },
-        Vec3 {
-            x: pixel_coord_to_mm(width - 1),
-            y: 0.0,
-            z: 0.0,
-        },
-        Vec3 {
-            x: pixel_coord_to_mm(width - 1),
-            y: pixel_coord_to_mm(height - 1),
-            z: 0.0,
-        },
-        Vec3 {
-            x: 0.0,
-            y: pixel_coord_to_mm(height - 1),
-            z: 0.0,
-        },
-        Vec3 {
-            x: 0.0,
-            y: 0.0,
-            z: -1.0,
-        },
-    );
-
-    let (width, height) = (width as usize, height as usize);
-
-    // Create left and right faces
-    for x in 0..width - 1 {
-        let a0 = thickness[0][x];
-        let a1 = thickness[0][x + 1];
-        let a2 = Vec3 {
-            x: a0.x,
-            y: a0.y,
-            z: 0.0,
-        };
-        let a3 = Vec3 {
-            x: a1.x,
-            y: a1.y,
-            z: 0.0,
-        };
-
-        let b0 = thickness[height - 1][x + 1];
-        let b1 = thickness[height - 1][x];
-        let b2 = Vec3 {
-            x: b0.x,
-            y: b0.y,
-            z: 0.0,
-        };
-        let b3 = Vec3 {
-            x: b1.x,
-            y: b1.y,
-            z: 0.0,
-        };
-
-        // Quad with (a0, a1, a2, a3)
-        let normal = Vec3 {
-            x: -1.0,
-            y: 0.0,
-            z: 0.0,
-        };
-        add_quad_with_normal(&mut mesh, a0, a1, a3, a2, normal);
-
-        // Quad with (b0, b1, b2, b3)
-
-        let normal = Vec3 {
-            x: 1.0,
-            y: 0.0,
-            z: 0.0,
-        };
-        add_quad_with_normal(&mut mesh, b0, b1, b3, b2, normal);
-    }
-
-    // Create top and bottom faces
-    for y in 0..height - 1 {
-        let a0 = thickness[y][0];
-        let a1 = thickness[y + 1][0];
-        let a2 = Vec3 {
-            x: a0.x,
-            y: a0.y,
-            z: 0.0,
-        };
-        let a3 = Vec3 {
-            x: a1.x,
-            y: a1.y,
-            z: 0.0,
-        };
-        let b0 = thickness[y][width - 1];
-        let b1 = thickness[y + 1][width - 1];
-        let b2 = Vec3 {
-            x: b0.x,
-            y: b0.y,
-            z: 0.0,
-        };
-        let b3 = Vec3 {
-            x: b1.x,
-            y: b1.y,
-            z: 0.0,
-        };
-
-        // Quad with (a0, a1, a2, a3)
-        let normal = Vec3 {
-            x: 0.0,
-            y: -1.0,
-            z: 0.0,
-        };
-        add_quad_with_normal(&mut mesh, a0, a1, a3, a2, normal);
-
-        // Quad with (b0, b1, b2, b3)
-        let normal = Vec3 {
-            x: 0.0,
-            y: 1.0,
-            z: 0.0,
-        };
-        add_quad_with_normal(&mut mesh, b0, b1, b3, b2, normal);
-    }
+mod export;
+mod mesh;
+mod physics;
+mod preview;
+mod quadtree;
+mod raster;
 
-    mesh
+use clap::{App, Arg};
+use export::Format;
+use raster::BlendMode;
+use std::path::Path;
+
+/// Parses a `"X,Y"` CLI value into a `(usize, usize)` pair.
+fn parse_position(s: &str) -> (usize, usize) {
+    let mut parts = s.splitn(2, ',');
+    let x = parts.next().unwrap().trim().parse().unwrap();
+    let y = parts.next().unwrap().trim().parse().unwrap();
+    (x, y)
 }
 
 fn main() {
@@ -336,7 +67,124 @@ fn main() {
                 .takes_value(true)
                 .default_value("0.5"),
         )
-        
+        .arg(
+            Arg::with_name("preview")
+                .long("preview")
+                .value_name("FILE.png")
+                .help("Also (or instead of the STL) render a grayscale PNG simulating the backlit print, so --thickness/--contrast can be tuned without printing")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("absorption")
+                .long("absorption")
+                .value_name("MU")
+                .help("Absorption coefficient per mm, shared by the backlit preview and --map beer (Beer-Lambert law)")
+                .takes_value(true)
+                .default_value("1.0"),
+        )
+        .arg(
+            Arg::with_name("map")
+                .long("map")
+                .value_name("MODE")
+                .help("Brightness-to-depth mapping: 'linear' or 'beer' (physically-correct, avoids crushed/washed-out backlit prints)")
+                .takes_value(true)
+                .possible_values(&["linear", "beer"])
+                .default_value("linear"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Output mesh format: 'stl-bin', 'stl-ascii', or 'obj'. Inferred from the output file's extension if unset")
+                .takes_value(true)
+                .possible_values(&["stl-bin", "stl-ascii", "obj"]),
+        )
+        .arg(
+            Arg::with_name("adaptive")
+                .long("adaptive")
+                .value_name("TOLERANCE")
+                .help("Build the front face from a restricted quadtree instead of one quad per pixel, splitting cells while the range of material depth inside them exceeds TOLERANCE (in mm); source brightness is not compared directly. Cuts triangle count on smooth gradients")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("text")
+                .long("text")
+                .value_name("STRING")
+                .help("Emboss/deboss this text into the lithophane (requires --font)")
+                .takes_value(true)
+                .requires("font"),
+        )
+        .arg(
+            Arg::with_name("font")
+                .long("font")
+                .value_name("FILE")
+                .help("TrueType/OpenType font file used to render --text")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("text-pos")
+                .long("text-pos")
+                .value_name("X,Y")
+                .help("Position of --text's top-left corner, in grid pixel coordinates (y=0 at the bottom)")
+                .takes_value(true)
+                .default_value("0,0"),
+        )
+        .arg(
+            Arg::with_name("text-size")
+                .long("text-size")
+                .value_name("PX")
+                .help("Font size of --text, in grid pixels")
+                .takes_value(true)
+                .default_value("32"),
+        )
+        .arg(
+            Arg::with_name("text-depth")
+                .long("text-depth")
+                .value_name("MM")
+                .help("How far --text raises (emboss) or pushes in (deboss) the surface, in mm")
+                .takes_value(true)
+                .default_value("1.0"),
+        )
+        .arg(
+            Arg::with_name("overlay")
+                .long("overlay")
+                .value_name("IMG")
+                .help("Composite an image's luminance onto the thickness grid as additional relief")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("overlay-depth")
+                .long("overlay-depth")
+                .value_name("MM")
+                .help("How far --overlay raises (emboss) or pushes in (deboss) the surface, in mm")
+                .takes_value(true)
+                .default_value("1.0"),
+        )
+        .arg(
+            Arg::with_name("emboss-mode")
+                .long("emboss-mode")
+                .value_name("MODE")
+                .help("How --text/--overlay are blended: 'emboss' (raise toward z=0, glows brighter backlit) or 'deboss' (push deeper)")
+                .takes_value(true)
+                .possible_values(&["emboss", "deboss"])
+                .default_value("emboss"),
+        )
+        .arg(
+            Arg::with_name("curve")
+                .long("curve")
+                .value_name("MODE")
+                .help("Wrap the generated relief onto a curved surface instead of a flat plane. Currently only 'cylinder' is supported (requires --radius)")
+                .takes_value(true)
+                .possible_values(&["cylinder"])
+                .requires("radius"),
+        )
+        .arg(
+            Arg::with_name("radius")
+                .long("radius")
+                .value_name("MM")
+                .help("Cylinder radius in mm for --curve cylinder; the model wraps into a full tube if the width spans the circumference")
+                .takes_value(true),
+        )
         .get_matches();
 
     // Parse arguments
@@ -345,16 +193,81 @@ fn main() {
     let width = matches.value_of("width").unwrap().parse::<f32>().unwrap();
     let thickness = matches.value_of("thickness").unwrap().parse::<f32>().unwrap();
     let contrast = matches.value_of("contrast").unwrap().parse::<f32>().unwrap();
+    let absorption = matches.value_of("absorption").unwrap().parse::<f32>().unwrap();
+    let map_mode = match matches.value_of("map").unwrap() {
+        "beer" => mesh::MapMode::Beer,
+        _ => mesh::MapMode::Linear,
+    };
+    if map_mode == mesh::MapMode::Beer || matches.value_of("preview").is_some() {
+        assert!(
+            absorption > 0.0,
+            "--absorption must be greater than 0 when using --map beer or --preview"
+        );
+    }
     // Load image
     let image = image::open(input_path).unwrap();
 
     // Mesh dimensions in mm
     let mesh_width = width;
 
+    // Compute the shared thickness grid, consumed by both the mesher and
+    // the backlit preview renderer so they agree on depth.
+    let mut thickness_grid =
+        mesh::compute_thickness_grid(&image, mesh_width, thickness, contrast, map_mode, absorption);
+
+    let emboss_mode = BlendMode::parse(matches.value_of("emboss-mode").unwrap()).unwrap();
+
+    if let Some(text) = matches.value_of("text") {
+        let font_path = matches.value_of("font").unwrap();
+        let text_pos = parse_position(matches.value_of("text-pos").unwrap());
+        let text_size = matches.value_of("text-size").unwrap().parse::<f32>().unwrap();
+        let text_depth = matches.value_of("text-depth").unwrap().parse::<f32>().unwrap();
+        raster::composite_text(
+            &mut thickness_grid,
+            font_path,
+            text,
+            text_pos,
+            text_size,
+            text_depth,
+            emboss_mode,
+        );
+    }
+
+    if let Some(overlay_path) = matches.value_of("overlay") {
+        let overlay_depth = matches.value_of("overlay-depth").unwrap().parse::<f32>().unwrap();
+        let overlay_image = image::open(overlay_path).unwrap();
+        raster::composite_overlay(&mut thickness_grid, &overlay_image, overlay_depth, emboss_mode);
+    }
+
+    if let Some(preview_path) = matches.value_of("preview") {
+        println!("Rendering backlit preview...");
+        let preview_img = preview::render_preview(&thickness_grid, absorption);
+        preview_img.save(preview_path).unwrap();
+    }
+
+    let adaptive = matches
+        .value_of("adaptive")
+        .map(|v| v.parse::<f32>().unwrap());
+
+    let curve = match matches.value_of("curve") {
+        Some("cylinder") => {
+            let radius = matches.value_of("radius").unwrap().parse::<f32>().unwrap();
+            assert!(radius > 0.0, "--radius must be greater than 0");
+            mesh::Curve::Cylinder { radius }
+        }
+        _ => mesh::Curve::Flat,
+    };
+
     // Generate mesh
-    let mesh = image_to_mesh(&image, mesh_width, thickness, contrast);
+    let mesh = mesh::image_to_mesh(&thickness_grid, mesh_width, adaptive, curve);
 
-    // Write STL file
-    let mut output_file = File::create(output_path).unwrap();
-    generate_stl_mesh(mesh, &mut output_file);
+    // Write the mesh out in the requested (or inferred) format
+    let output_path = Path::new(output_path);
+    let format = match matches.value_of("format") {
+        Some(name) => Format::parse(name).unwrap(),
+        None => Format::infer(output_path),
+    };
+    export::exporter_for(format)
+        .export(mesh, output_path)
+        .unwrap();
 }
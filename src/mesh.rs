@@ -0,0 +1,529 @@
+use crate::physics::transmittance;
+use image::{DynamicImage, GenericImageView};
+use indicatif::ProgressIterator;
+
+/// How source brightness is mapped to material depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapMode {
+    /// Depth varies linearly with brightness. Simple, but since
+    /// transmitted light falls off exponentially with depth (Beer-Lambert),
+    /// this makes backlit prints look crushed/washed out.
+    Linear,
+    /// Depth is chosen so that equal steps of source brightness produce
+    /// equal steps of *transmitted* brightness once backlit, by inverting
+    /// the Beer-Lambert law.
+    Beer,
+}
+
+/// Overall geometry the mesh is wrapped onto.
+#[derive(Debug, Clone, Copy)]
+pub enum Curve {
+    /// The relief sits on a flat plane, as generated.
+    Flat,
+    /// The relief is wrapped around a cylinder of the given `radius` (mm),
+    /// the common form for lamp-shade lithophanes. `x` becomes an arc
+    /// angle around the cylinder's axis; `y` stays the axis coordinate.
+    Cylinder { radius: f32 },
+}
+
+/// Whether wrapping `mesh_width` mm around a cylinder of `radius` mm
+/// closes the arc into a full tube (within floating point tolerance).
+fn cylinder_wraps_fully(mesh_width: f32, radius: f32) -> bool {
+    (mesh_width / radius - std::f32::consts::TAU).abs() < 1e-3
+}
+
+/// Appends a duplicate of column 0 (shifted one `mesh_width` to the right)
+/// to `thickness`, so the last real column and this new one are exactly
+/// one pixel-step apart, the same as every other pair of neighbouring
+/// columns. Used when a `Curve::Cylinder` spans a full 2*pi: the
+/// duplicate's shifted `x` warps to `theta = mesh_width / radius = tau`,
+/// which lands it on the same world position as column 0, so the
+/// front/back tessellation and the axial rim walls get a real closing
+/// strip instead of stopping one pixel-step short of the full circle and
+/// leaving an open seam.
+fn close_cylinder_seam(thickness: &ThicknessGrid, mesh_width: f32) -> ThicknessGrid {
+    thickness
+        .iter()
+        .map(|row| {
+            let mut row = row.clone();
+            let mut closing = row[0];
+            closing.x = mesh_width;
+            row.push(closing);
+            row
+        })
+        .collect()
+}
+
+/// Computes a triangle's face normal from its three vertices.
+pub fn normal(v0: Vec3, v1: Vec3, v2: Vec3) -> Vec3 {
+    Vec3 {
+        x: (v1.y - v0.y) * (v2.z - v0.z) - (v1.z - v0.z) * (v2.y - v0.y),
+        y: (v1.z - v0.z) * (v2.x - v0.x) - (v1.x - v0.x) * (v2.z - v0.z),
+        z: (v1.x - v0.x) * (v2.y - v0.y) - (v1.y - v0.y) * (v2.x - v0.x),
+    }
+}
+
+/// Maps a flat-mesh vertex onto `curve`'s geometry.
+fn warp_vertex(v: Vec3, curve: Curve) -> Vec3 {
+    match curve {
+        Curve::Flat => v,
+        Curve::Cylinder { radius } => {
+            let theta = v.x / radius;
+            Vec3 {
+                x: (radius + v.z) * theta.cos(),
+                y: v.y,
+                z: (radius + v.z) * theta.sin(),
+            }
+        }
+    }
+}
+
+/// Wraps a flat `mesh` onto `curve`, recomputing every triangle's normal
+/// from its warped vertices (the hardcoded axis-aligned normals used for
+/// the flat side/back walls are only valid before warping).
+fn apply_curve(mesh: Mesh, curve: Curve) -> Mesh {
+    match curve {
+        Curve::Flat => mesh,
+        Curve::Cylinder { .. } => mesh
+            .into_iter()
+            .map(|t| {
+                let v0 = warp_vertex(t.v0, curve);
+                let v1 = warp_vertex(t.v1, curve);
+                let v2 = warp_vertex(t.v2, curve);
+                Triangle {
+                    normal: normal(v0, v1, v2),
+                    v0,
+                    v1,
+                    v2,
+                }
+            })
+            .collect(),
+    }
+}
+
+#[derive(Debug)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub fn to_le_bytes(&self) -> [u8; 12] {
+        let mut bytes = [0; 12];
+        bytes[0..4].copy_from_slice(&self.x.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.y.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.z.to_le_bytes());
+        bytes
+    }
+}
+
+impl Copy for Vec3 {}
+
+impl Clone for Vec3 {
+    fn clone(&self) -> Self {
+        Vec3 {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+}
+
+pub struct Triangle {
+    pub normal: Vec3,
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+}
+
+pub type Mesh = Vec<Triangle>;
+
+/// A `height x width` grid of vertices carrying the per-pixel material
+/// depth (in `z`) alongside its planar position in mm. Shared between the
+/// mesher and the backlit preview renderer so both agree on exactly how
+/// brightness maps to thickness.
+pub type ThicknessGrid = Vec<Vec<Vec3>>;
+
+fn get_pixel_brightness(r: u8, g: u8, b: u8) -> f32 {
+    // Use the standard formula for brightness
+    let brightness = (r as f32 * 0.299) + (g as f32 * 0.587) + (b as f32 * 0.114);
+    brightness / 255.0
+}
+
+/// Inverts the Beer-Lambert law to pick the depth `z` that makes a source
+/// pixel of brightness `b` transmit proportionally once backlit: darker
+/// pixels get thicker material and brighter pixels get thinner material,
+/// but spaced so that equal steps in `b` give equal steps in transmitted
+/// intensity, rather than the crushed/washed-out falloff of a linear map.
+fn beer_brightness_to_mm(brightness: f32, mesh_thickness: f32, contrast: f32, mu: f32) -> f32 {
+    let z_min = mesh_thickness * (1.0 - contrast);
+    let z_max = mesh_thickness;
+    let b_lo = transmittance(z_max, mu);
+    let b_hi = transmittance(z_min, mu);
+
+    let b = b_lo + brightness * (b_hi - b_lo);
+    -b.ln() / mu
+}
+
+/// Computes the per-pixel thickness grid for `img`. In `MapMode::Linear`,
+/// brightness maps to depth linearly: darker pixels are thicker, brighter
+/// pixels are thinner, down to `mesh_thickness * (1 - contrast)` at full
+/// brightness. In `MapMode::Beer`, the depth is instead chosen so that the
+/// print's *transmitted* brightness under backlight varies linearly with
+/// the source brightness (see `beer_brightness_to_mm`); `absorption` is the
+/// same Beer-Lambert coefficient used by the backlit preview renderer, so
+/// the two agree.
+pub fn compute_thickness_grid(
+    img: &DynamicImage,
+    mesh_width: f32,
+    mesh_thickness: f32,
+    contrast: f32,
+    map_mode: MapMode,
+    absorption: f32,
+) -> ThicknessGrid {
+    let (width, height) = img.dimensions();
+
+    let brightness_to_mm = |brightness: f32| match map_mode {
+        MapMode::Linear => mesh_thickness - (brightness * contrast * mesh_thickness),
+        MapMode::Beer => beer_brightness_to_mm(brightness, mesh_thickness, contrast, absorption),
+    };
+
+    let pixel_coord_to_mm = |val: u32| val as f32 * mesh_width / (width as f32);
+    let get_thickness_vec3 = |x, y| {
+        let pixel = img.get_pixel(x, height - y - 1);
+        let brightness = get_pixel_brightness(pixel[0], pixel[1], pixel[2]);
+        let mm = brightness_to_mm(brightness);
+        Vec3 {
+            x: pixel_coord_to_mm(x),
+            y: pixel_coord_to_mm(y),
+            z: mm,
+        }
+    };
+
+    println!("Computing brightness...");
+    (0..height)
+        .progress()
+        .map(|y| {
+            (0..width)
+                .map(|x| get_thickness_vec3(x, y))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Builds the watertight lithophane mesh (front relief, back, and four
+/// side walls) from a precomputed `thickness` grid. When `adaptive` is
+/// `Some(tolerance)`, the front relief is tessellated from a restricted
+/// quadtree (see the `quadtree` module) instead of two triangles per
+/// pixel, which cuts the triangle count dramatically on smooth gradients
+/// while preserving sharp edges. `curve` optionally wraps the finished
+/// mesh around a cylinder instead of leaving it flat; when it spans a
+/// full 2*pi, a closing column is tessellated in so the surface actually
+/// reaches all the way around instead of leaving a seam-width gap.
+pub fn image_to_mesh(
+    thickness: &ThicknessGrid,
+    mesh_width: f32,
+    adaptive: Option<f32>,
+    curve: Curve,
+) -> Mesh {
+    // When the cylinder spans a full revolution, tessellate an extra
+    // closing column so the front/back faces and rim walls actually reach
+    // all the way around instead of stopping one pixel-step short of it.
+    let full_wrap = matches!(curve, Curve::Cylinder { radius } if cylinder_wraps_fully(mesh_width, radius));
+    let closed_thickness;
+    let thickness: &ThicknessGrid = if full_wrap {
+        closed_thickness = close_cylinder_seam(thickness, mesh_width);
+        &closed_thickness
+    } else {
+        thickness
+    };
+
+    let height = thickness.len();
+    let width = thickness[0].len();
+    let pixel_coord_to_mm = |val: usize| val as f32 * mesh_width / (width as f32);
+
+    let (mut mesh, boundary) = if let Some(tolerance) = adaptive {
+        crate::quadtree::build_front_face(thickness, tolerance)
+    } else {
+        (
+            Mesh::new(),
+            crate::quadtree::Boundary {
+                top: (0..width).collect(),
+                bottom: (0..width).collect(),
+                left: (0..height).collect(),
+                right: (0..height).collect(),
+            },
+        )
+    };
+
+    println!("Generating mesh...");
+
+    let add_quad = |mesh: &mut Mesh, v0: Vec3, v1: Vec3, v2: Vec3, v3: Vec3| {
+        let normal = normal(v0, v1, v2);
+        mesh.push(Triangle { normal, v0, v1, v2 });
+        mesh.push(Triangle {
+            normal,
+            v0,
+            v1: v2,
+            v2: v3,
+        });
+    };
+
+    let add_quad_with_normal =
+        |mesh: &mut Mesh, v0: Vec3, v1: Vec3, v2: Vec3, v3: Vec3, n: Vec3| {
+            mesh.push(Triangle {
+                normal: n,
+                v0,
+                v1,
+                v2,
+            });
+            mesh.push(Triangle {
+                normal: n,
+                v0,
+                v1: v2,
+                v2: v3,
+            });
+        };
+
+    // Create front face by tesselating a plane with the given thickness,
+    // unless the caller already built it adaptively above.
+    if adaptive.is_none() {
+        for y in (0..height - 1).progress() {
+            for x in 0..width - 1 {
+                let v0 = thickness[y][x];
+                let v1 = thickness[y][x + 1];
+                let v2 = thickness[y + 1][x + 1];
+                let v3 = thickness[y + 1][x];
+
+                add_quad(&mut mesh, v0, v1, v2, v3);
+            }
+        }
+    }
+
+    // Create back face. When flat, a single quad spanning the image is a
+    // flat plane either way, so keep the cheap single-quad form. Once
+    // warped onto a curve, though, the interior of that one giant quad
+    // would cut a straight chord between its far-apart corners instead of
+    // following the curve, slicing through the front relief. So when
+    // curved, tessellate it per-column just like the front face, and let
+    // `apply_curve` warp (and renormal) every vertex individually.
+    if matches!(curve, Curve::Flat) {
+        add_quad_with_normal(
+            &mut mesh,
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vec3 {
+                x: pixel_coord_to_mm(width - 1),
+                y: 0.0,
+                z: 0.0,
+            },
+            Vec3 {
+                x: pixel_coord_to_mm(width - 1),
+                y: pixel_coord_to_mm(height - 1),
+                z: 0.0,
+            },
+            Vec3 {
+                x: 0.0,
+                y: pixel_coord_to_mm(height - 1),
+                z: 0.0,
+            },
+            Vec3 {
+                x: 0.0,
+                y: 0.0,
+                z: -1.0,
+            },
+        );
+    } else {
+        for y in 0..height - 1 {
+            for x in 0..width - 1 {
+                let p0 = thickness[y][x];
+                let p1 = thickness[y][x + 1];
+                let p2 = thickness[y + 1][x + 1];
+                let p3 = thickness[y + 1][x];
+                let v0 = Vec3 {
+                    x: p0.x,
+                    y: p0.y,
+                    z: 0.0,
+                };
+                let v1 = Vec3 {
+                    x: p1.x,
+                    y: p1.y,
+                    z: 0.0,
+                };
+                let v2 = Vec3 {
+                    x: p2.x,
+                    y: p2.y,
+                    z: 0.0,
+                };
+                let v3 = Vec3 {
+                    x: p3.x,
+                    y: p3.y,
+                    z: 0.0,
+                };
+
+                // Reversed winding vs. the front face, so the normal faces
+                // -z (outward, away from the slab) before warping.
+                add_quad(&mut mesh, v0, v3, v2, v1);
+            }
+        }
+    }
+
+    // Create left and right faces, driven by the quadtree's boundary
+    // vertices along y=0/y=height-1 (or every pixel, when not adaptive) so
+    // the wall never diverges from a coarsened front-face edge.
+    for pair in boundary.top.windows(2) {
+        let (x0, x1) = (pair[0], pair[1]);
+        let a0 = thickness[0][x0];
+        let a1 = thickness[0][x1];
+        let a2 = Vec3 {
+            x: a0.x,
+            y: a0.y,
+            z: 0.0,
+        };
+        let a3 = Vec3 {
+            x: a1.x,
+            y: a1.y,
+            z: 0.0,
+        };
+
+        // Quad with (a0, a1, a2, a3)
+        let normal = Vec3 {
+            x: -1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        add_quad_with_normal(&mut mesh, a0, a1, a3, a2, normal);
+    }
+    for pair in boundary.bottom.windows(2) {
+        let (x0, x1) = (pair[0], pair[1]);
+        let b0 = thickness[height - 1][x1];
+        let b1 = thickness[height - 1][x0];
+        let b2 = Vec3 {
+            x: b0.x,
+            y: b0.y,
+            z: 0.0,
+        };
+        let b3 = Vec3 {
+            x: b1.x,
+            y: b1.y,
+            z: 0.0,
+        };
+
+        // Quad with (b0, b1, b2, b3)
+        let normal = Vec3 {
+            x: 1.0,
+            y: 0.0,
+            z: 0.0,
+        };
+        add_quad_with_normal(&mut mesh, b0, b1, b3, b2, normal);
+    }
+
+    // Create top and bottom faces (the x=0 / x=width-1 seam). When wrapped
+    // around a cylinder that spans a full 2*pi, column 0 and the closing
+    // column appended above land on the same world position, so a wall
+    // between them would just be a zero-area sliver sitting inside the
+    // finished tube; it's dropped since the front/back faces (and rim
+    // walls) already close the seam via that shared column.
+    if !full_wrap {
+        for pair in boundary.left.windows(2) {
+            let (y0, y1) = (pair[0], pair[1]);
+            let a0 = thickness[y0][0];
+            let a1 = thickness[y1][0];
+            let a2 = Vec3 {
+                x: a0.x,
+                y: a0.y,
+                z: 0.0,
+            };
+            let a3 = Vec3 {
+                x: a1.x,
+                y: a1.y,
+                z: 0.0,
+            };
+
+            // Quad with (a0, a1, a2, a3)
+            let normal = Vec3 {
+                x: 0.0,
+                y: -1.0,
+                z: 0.0,
+            };
+            add_quad_with_normal(&mut mesh, a0, a1, a3, a2, normal);
+        }
+        for pair in boundary.right.windows(2) {
+            let (y0, y1) = (pair[0], pair[1]);
+            let b0 = thickness[y0][width - 1];
+            let b1 = thickness[y1][width - 1];
+            let b2 = Vec3 {
+                x: b0.x,
+                y: b0.y,
+                z: 0.0,
+            };
+            let b3 = Vec3 {
+                x: b1.x,
+                y: b1.y,
+                z: 0.0,
+            };
+
+            // Quad with (b0, b1, b2, b3)
+            let normal = Vec3 {
+                x: 0.0,
+                y: 1.0,
+                z: 0.0,
+            };
+            add_quad_with_normal(&mut mesh, b0, b1, b3, b2, normal);
+        }
+    }
+
+    apply_curve(mesh, curve)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat_grid(width: usize, height: usize, mesh_width: f32) -> ThicknessGrid {
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| Vec3 {
+                        x: x as f32 * mesh_width / width as f32,
+                        y: y as f32,
+                        z: 1.0,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Regression for a `--curve cylinder` where `--width`/`--radius` are
+    /// chosen to close exactly: the discretized mesh's last column always
+    /// falls one pixel-step short of a full revolution, so dropping the
+    /// left/right seam walls (because the geometry genuinely does close)
+    /// must be paired with an explicit closing strip tessellated back to
+    /// column 0 -- otherwise the seam walls are gone and nothing replaces
+    /// them, leaving a full-height open slit.
+    #[test]
+    fn cylinder_full_wrap_closes_without_a_seam_gap() {
+        let width = 4;
+        let height = 2;
+        let radius = 10.0;
+        let mesh_width = radius * std::f32::consts::TAU;
+        assert!(cylinder_wraps_fully(mesh_width, radius));
+
+        let thickness = flat_grid(width, height, mesh_width);
+        let mesh = image_to_mesh(&thickness, mesh_width, None, Curve::Cylinder { radius });
+
+        // The front and back faces each tessellate `height - 1` rows of
+        // `width` quads (including the closing one), the two axial rim
+        // walls each tessellate `width` quads around the full
+        // circumference, and the left/right seam walls contribute
+        // nothing since the mesh genuinely closes -- so the total
+        // triangle count reduces to `4 * width * height`. Without the
+        // closing strip, the front/back/rim quad counts would each be
+        // `width - 1`, one short, leaving the seam unfilled.
+        assert_eq!(mesh.len(), 4 * width * height);
+    }
+}
@@ -0,0 +1,10 @@
+//! Beer-Lambert transmission model shared by the backlit preview renderer
+//! and the physically-correct thickness mapping, so both agree on exactly
+//! how material depth attenuates backlight.
+
+/// Fraction of backlight transmitted through `depth_mm` of material with
+/// absorption coefficient `mu` (per mm), per the Beer-Lambert law:
+/// `T = I0 * exp(-mu * z)` with `I0 = 1`.
+pub fn transmittance(depth_mm: f32, mu: f32) -> f32 {
+    (-mu * depth_mm).exp()
+}
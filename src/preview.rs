@@ -0,0 +1,45 @@
+use crate::mesh::ThicknessGrid;
+use crate::physics::transmittance;
+use image::{GrayImage, Luma};
+
+/// Renders a grayscale WYSIWYG preview of how the lithophane will look
+/// backlit: applies the Beer-Lambert law to the per-pixel material
+/// thickness, then normalizes the transmitted intensity across the image
+/// to `[0, 255]` so the full dynamic range is visible on screen.
+pub fn render_preview(thickness: &ThicknessGrid, absorption: f32) -> GrayImage {
+    let height = thickness.len();
+    let width = thickness[0].len();
+
+    let transmitted: Vec<Vec<f32>> = thickness
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|v| transmittance(v.z, absorption))
+                .collect()
+        })
+        .collect();
+
+    let max_t = transmitted
+        .iter()
+        .flatten()
+        .cloned()
+        .fold(f32::MIN, f32::max);
+    let min_t = transmitted
+        .iter()
+        .flatten()
+        .cloned()
+        .fold(f32::MAX, f32::min);
+    let range = (max_t - min_t).max(f32::EPSILON);
+
+    let mut img = GrayImage::new(width as u32, height as u32);
+    for (y, row) in transmitted.iter().enumerate() {
+        for (x, &t) in row.iter().enumerate() {
+            let normalized = (((t - min_t) / range) * 255.0).round() as u8;
+            // The thickness grid is stored bottom-up (see
+            // `compute_thickness_grid`), so flip it back to match the
+            // source image's orientation.
+            img.put_pixel(x as u32, (height - 1 - y) as u32, Luma([normalized]));
+        }
+    }
+    img
+}
@@ -0,0 +1,333 @@
+use crate::mesh::{normal, Mesh, ThicknessGrid, Triangle, Vec3};
+use std::collections::{BTreeSet, HashMap};
+
+/// The pixel coordinates at which the quadtree's front face actually has
+/// a vertex along each of the image's four edges, ascending. Driving the
+/// side walls from these (rather than every pixel) keeps them
+/// attached to the coarsened front-face edge instead of the unreduced
+/// per-pixel one.
+pub struct Boundary {
+    pub top: Vec<usize>,
+    pub bottom: Vec<usize>,
+    pub left: Vec<usize>,
+    pub right: Vec<usize>,
+}
+
+/// A leaf (or candidate) cell of the restricted quadtree: a square patch
+/// of `size x size` pixel-quads with its top-left corner at grid
+/// coordinates `(x, y)`.
+#[derive(Debug, Clone, Copy)]
+struct Cell {
+    x: usize,
+    y: usize,
+    size: usize,
+    level: u32,
+}
+
+fn next_pow2(n: usize) -> usize {
+    let mut p = 1;
+    while p < n {
+        p *= 2;
+    }
+    p
+}
+
+/// Min and max of the grid's `z` (material depth) inside the pixel range
+/// `[x, x+size] x [y, y+size]`, clamped to the grid's actual bounds.
+fn z_range(thickness: &ThicknessGrid, max_x: usize, max_y: usize, cell: &Cell) -> (f32, f32) {
+    let mut lo = f32::MAX;
+    let mut hi = f32::MIN;
+    for dy in 0..=cell.size {
+        let y = (cell.y + dy).min(max_y);
+        for dx in 0..=cell.size {
+            let x = (cell.x + dx).min(max_x);
+            let z = thickness[y][x].z;
+            lo = lo.min(z);
+            hi = hi.max(z);
+        }
+    }
+    (lo, hi)
+}
+
+/// Recursively splits `cell` while the brightness (here, material depth)
+/// range inside it exceeds `tolerance`, collecting the resulting leaves.
+/// Cells entirely past the image's actual extent (padding introduced by
+/// rounding up to a power of two) are dropped rather than kept as leaves.
+fn split(
+    thickness: &ThicknessGrid,
+    max_x: usize,
+    max_y: usize,
+    cell: Cell,
+    tolerance: f32,
+    out: &mut Vec<Cell>,
+) {
+    if cell.x > max_x || cell.y > max_y {
+        return;
+    }
+
+    if cell.size > 1 {
+        let (lo, hi) = z_range(thickness, max_x, max_y, &cell);
+        if hi - lo > tolerance {
+            let half = cell.size / 2;
+            let level = cell.level + 1;
+            split(
+                thickness,
+                max_x,
+                max_y,
+                Cell { x: cell.x, y: cell.y, size: half, level },
+                tolerance,
+                out,
+            );
+            split(
+                thickness,
+                max_x,
+                max_y,
+                Cell { x: cell.x + half, y: cell.y, size: half, level },
+                tolerance,
+                out,
+            );
+            split(
+                thickness,
+                max_x,
+                max_y,
+                Cell { x: cell.x, y: cell.y + half, size: half, level },
+                tolerance,
+                out,
+            );
+            split(
+                thickness,
+                max_x,
+                max_y,
+                Cell { x: cell.x + half, y: cell.y + half, size: half, level },
+                tolerance,
+                out,
+            );
+            return;
+        }
+    }
+
+    out.push(cell);
+}
+
+/// Enforces the standard quadtree balancing invariant (adjacent leaves
+/// differ by at most one level) by force-splitting any leaf that has a
+/// neighbour more than one level finer, until the tree stops changing.
+fn balance(thickness: &ThicknessGrid, max_x: usize, max_y: usize, mut leaves: Vec<Cell>) -> Vec<Cell> {
+    loop {
+        let sizes: HashMap<(usize, usize), usize> =
+            leaves.iter().map(|c| ((c.x, c.y), c.size)).collect();
+
+        let mut changed = false;
+        let mut next = Vec::with_capacity(leaves.len());
+
+        for cell in leaves {
+            let half = cell.size / 2;
+            // A neighbour is "too fine" if it's subdivided below half
+            // this cell's size, which would make the
+            // neighbour's level more than one deeper than this cell's.
+            let has_finer_neighbor = half > 0
+                && [
+                    // left / right columns
+                    (cell.x.wrapping_sub(half), cell.y),
+                    (cell.x.wrapping_sub(half), cell.y + half),
+                    (cell.x + cell.size, cell.y),
+                    (cell.x + cell.size, cell.y + half),
+                    // top / bottom rows
+                    (cell.x, cell.y.wrapping_sub(half)),
+                    (cell.x + half, cell.y.wrapping_sub(half)),
+                    (cell.x, cell.y + cell.size),
+                    (cell.x + half, cell.y + cell.size),
+                ]
+                .iter()
+                .any(|pos| matches!(sizes.get(pos), Some(&s) if s < half));
+
+            if has_finer_neighbor && cell.size > 1 {
+                changed = true;
+                let level = cell.level + 1;
+                split(
+                    thickness,
+                    max_x,
+                    max_y,
+                    Cell { x: cell.x, y: cell.y, size: half, level },
+                    f32::MAX,
+                    &mut next,
+                );
+                split(
+                    thickness,
+                    max_x,
+                    max_y,
+                    Cell { x: cell.x + half, y: cell.y, size: half, level },
+                    f32::MAX,
+                    &mut next,
+                );
+                split(
+                    thickness,
+                    max_x,
+                    max_y,
+                    Cell { x: cell.x, y: cell.y + half, size: half, level },
+                    f32::MAX,
+                    &mut next,
+                );
+                split(
+                    thickness,
+                    max_x,
+                    max_y,
+                    Cell { x: cell.x + half, y: cell.y + half, size: half, level },
+                    f32::MAX,
+                    &mut next,
+                );
+            } else {
+                next.push(cell);
+            }
+        }
+
+        leaves = next;
+        if !changed {
+            return leaves;
+        }
+    }
+}
+
+fn vertex_at(thickness: &ThicknessGrid, max_x: usize, max_y: usize, x: usize, y: usize) -> Vec3 {
+    thickness[y.min(max_y)][x.min(max_x)]
+}
+
+fn same_pos(a: &Vec3, b: &Vec3) -> bool {
+    a.x == b.x && a.y == b.y && a.z == b.z
+}
+
+/// Collects the x (or y) boundary positions where `leaves` have a vertex
+/// along each of the image's four edges.
+fn boundary_of(leaves: &[Cell], max_x: usize, max_y: usize) -> Boundary {
+    let mut top = BTreeSet::new();
+    let mut bottom = BTreeSet::new();
+    let mut left = BTreeSet::new();
+    let mut right = BTreeSet::new();
+
+    for cell in leaves {
+        let x0 = cell.x;
+        let x1 = (cell.x + cell.size).min(max_x);
+        let y0 = cell.y;
+        let y1 = (cell.y + cell.size).min(max_y);
+
+        if cell.y == 0 {
+            top.insert(x0);
+            top.insert(x1);
+        }
+        if cell.y + cell.size >= max_y {
+            bottom.insert(x0);
+            bottom.insert(x1);
+        }
+        if cell.x == 0 {
+            left.insert(y0);
+            left.insert(y1);
+        }
+        if cell.x + cell.size >= max_x {
+            right.insert(y0);
+            right.insert(y1);
+        }
+    }
+
+    Boundary {
+        top: top.into_iter().collect(),
+        bottom: bottom.into_iter().collect(),
+        left: left.into_iter().collect(),
+        right: right.into_iter().collect(),
+    }
+}
+
+/// Builds the front face of the lithophane by tessellating a restricted
+/// quadtree over the brightness (material depth) field instead of emitting
+/// two triangles per pixel: flat regions collapse into large quads, while
+/// detailed regions stay finely subdivided. Cracks along edges where a
+/// coarse cell borders finer neighbours are stitched by fanning to the
+/// finer edge's shared midpoint vertex, keeping the mesh watertight. Also
+/// returns the `Boundary` vertices along the image's four edges, so the
+/// caller can drive the side walls from the same coarsened edge
+/// instead of the per-pixel grid.
+pub fn build_front_face(thickness: &ThicknessGrid, tolerance: f32) -> (Mesh, Boundary) {
+    let height = thickness.len();
+    let width = thickness[0].len();
+    let max_x = width - 1;
+    let max_y = height - 1;
+
+    let extent = next_pow2(max_x.max(max_y));
+    let mut leaves = Vec::new();
+    split(
+        thickness,
+        max_x,
+        max_y,
+        Cell { x: 0, y: 0, size: extent, level: 0 },
+        tolerance,
+        &mut leaves,
+    );
+    leaves.retain(|c| c.x < width - 1 && c.y < height - 1);
+    let leaves = balance(thickness, max_x, max_y, leaves);
+
+    let sizes: HashMap<(usize, usize), usize> =
+        leaves.iter().map(|c| ((c.x, c.y), c.size)).collect();
+    let is_finer_neighbor_at = |pos: (usize, usize), half: usize| {
+        half > 0 && matches!(sizes.get(&pos), Some(&s) if s == half)
+    };
+
+    let mut mesh = Mesh::new();
+
+    println!("Building adaptive quadtree mesh...");
+    for cell in &leaves {
+        let half = cell.size / 2;
+
+        let tl = vertex_at(thickness, max_x, max_y, cell.x, cell.y);
+        let tr = vertex_at(thickness, max_x, max_y, cell.x + cell.size, cell.y);
+        let br = vertex_at(thickness, max_x, max_y, cell.x + cell.size, cell.y + cell.size);
+        let bl = vertex_at(thickness, max_x, max_y, cell.x, cell.y + cell.size);
+
+        let need_top = is_finer_neighbor_at((cell.x, cell.y.wrapping_sub(half)), half)
+            || is_finer_neighbor_at((cell.x + half, cell.y.wrapping_sub(half)), half);
+        let need_right = is_finer_neighbor_at((cell.x + cell.size, cell.y), half)
+            || is_finer_neighbor_at((cell.x + cell.size, cell.y + half), half);
+        let need_bottom = is_finer_neighbor_at((cell.x, cell.y + cell.size), half)
+            || is_finer_neighbor_at((cell.x + half, cell.y + cell.size), half);
+        let need_left = is_finer_neighbor_at((cell.x.wrapping_sub(half), cell.y), half)
+            || is_finer_neighbor_at((cell.x.wrapping_sub(half), cell.y + half), half);
+
+        // Walk the leaf's perimeter clockwise from its top-left corner,
+        // splicing in a midpoint vertex wherever a finer neighbour shares
+        // one, so the crack between the two resolutions is triangulated
+        // rather than left as a gap or a T-junction.
+        let mut perimeter = vec![tl];
+        if need_top {
+            perimeter.push(vertex_at(thickness, max_x, max_y, cell.x + half, cell.y));
+        }
+        perimeter.push(tr);
+        if need_right {
+            perimeter.push(vertex_at(thickness, max_x, max_y, cell.x + cell.size, cell.y + half));
+        }
+        perimeter.push(br);
+        if need_bottom {
+            perimeter.push(vertex_at(thickness, max_x, max_y, cell.x + half, cell.y + cell.size));
+        }
+        perimeter.push(bl);
+        if need_left {
+            perimeter.push(vertex_at(thickness, max_x, max_y, cell.x, cell.y + half));
+        }
+
+        // A midpoint can land exactly on a corner once both are clamped to
+        // the image's real bounds (cells that overrun the power-of-two
+        // padded extent), which would otherwise fan a zero-area triangle.
+        perimeter.dedup_by(|a, b| same_pos(a, b));
+        if perimeter.len() > 1 && same_pos(&perimeter[0], perimeter.last().unwrap()) {
+            perimeter.pop();
+        }
+
+        // Fan-triangulate the (possibly crack-stitched) perimeter from its
+        // first vertex.
+        for i in 1..perimeter.len() - 1 {
+            let (v0, v1, v2) = (perimeter[0], perimeter[i], perimeter[i + 1]);
+            let n = normal(v0, v1, v2);
+            mesh.push(Triangle { normal: n, v0, v1, v2 });
+        }
+    }
+
+    let boundary = boundary_of(&leaves, max_x, max_y);
+    (mesh, boundary)
+}
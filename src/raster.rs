@@ -0,0 +1,160 @@
+use crate::mesh::ThicknessGrid;
+use image::{DynamicImage, GenericImageView, imageops::FilterType};
+use rusttype::{point, Font, Scale};
+use std::fs;
+
+/// How rasterized coverage is blended into the thickness grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Raise geometry toward `z = 0`, thinning the slab so covered areas
+    /// glow brighter when backlit.
+    Emboss,
+    /// Push geometry deeper, so covered areas read darker when backlit.
+    Deboss,
+}
+
+impl BlendMode {
+    pub fn parse(name: &str) -> Option<BlendMode> {
+        match name {
+            "emboss" => Some(BlendMode::Emboss),
+            "deboss" => Some(BlendMode::Deboss),
+            _ => None,
+        }
+    }
+}
+
+/// An offscreen, single-channel coverage buffer (0.0..=1.0 per pixel),
+/// positioned at `origin` in grid coordinates, that gets blended into a
+/// `ThicknessGrid` once rasterization is done.
+struct CoverageBuffer {
+    origin: (usize, usize),
+    width: usize,
+    height: usize,
+    coverage: Vec<f32>,
+}
+
+impl CoverageBuffer {
+    fn new(origin: (usize, usize), width: usize, height: usize) -> Self {
+        CoverageBuffer {
+            origin,
+            width,
+            height,
+            coverage: vec![0.0; width * height],
+        }
+    }
+
+    fn accumulate(&mut self, x: i32, y: i32, value: f32) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        let idx = y as usize * self.width + x as usize;
+        self.coverage[idx] = (self.coverage[idx] + value).min(1.0);
+    }
+
+    /// Blends this buffer's coverage into `thickness`, raising or
+    /// lowering each covered pixel's depth by up to `depth` mm. The buffer
+    /// itself is top-down (rasterized glyphs and `image::open`'d overlays
+    /// both read that way), but `ThicknessGrid` rows are bottom-up (see
+    /// `compute_thickness_grid`), so `origin` anchors the buffer's *top*
+    /// row and rows count down from there as `y` increases, the same way
+    /// `preview::render_preview` flips rows back when writing a PNG.
+    fn composite_onto(&self, thickness: &mut ThicknessGrid, depth: f32, mode: BlendMode) {
+        let grid_height = thickness.len();
+        let grid_width = thickness[0].len();
+
+        for y in 0..self.height {
+            let gy = match self.origin.1.checked_sub(y) {
+                Some(gy) => gy,
+                None => continue,
+            };
+            if gy >= grid_height {
+                continue;
+            }
+            for x in 0..self.width {
+                let gx = self.origin.0 + x;
+                if gx >= grid_width {
+                    continue;
+                }
+                let c = self.coverage[y * self.width + x];
+                if c <= 0.0 {
+                    continue;
+                }
+                let delta = c * depth;
+                let z = &mut thickness[gy][gx].z;
+                *z = match mode {
+                    BlendMode::Emboss => (*z - delta).max(0.0),
+                    BlendMode::Deboss => *z + delta,
+                };
+            }
+        }
+    }
+}
+
+/// Rasterizes `text` in `font` at `size_px`, positioned with its top-left
+/// corner at `origin` (in grid/pixel coordinates), and blends the glyph
+/// coverage into `thickness`.
+pub fn composite_text(
+    thickness: &mut ThicknessGrid,
+    font_path: &str,
+    text: &str,
+    origin: (usize, usize),
+    size_px: f32,
+    depth: f32,
+    mode: BlendMode,
+) {
+    let font_bytes = fs::read(font_path).unwrap();
+    let font = Font::try_from_bytes(&font_bytes).expect("invalid font file");
+
+    let scale = Scale::uniform(size_px);
+    let v_metrics = font.v_metrics(scale);
+    let start = point(0.0, v_metrics.ascent);
+
+    let glyphs: Vec<_> = font.layout(text, scale, start).collect();
+    let width = glyphs
+        .iter()
+        .rev()
+        .find_map(|g| g.pixel_bounding_box().map(|b| b.max.x))
+        .unwrap_or(0)
+        .max(1) as usize;
+    let height = (v_metrics.ascent - v_metrics.descent).ceil().max(1.0) as usize;
+
+    let mut buffer = CoverageBuffer::new(origin, width, height);
+    for glyph in &glyphs {
+        if let Some(bb) = glyph.pixel_bounding_box() {
+            glyph.draw(|x, y, v| {
+                buffer.accumulate(bb.min.x + x as i32, bb.min.y + y as i32, v);
+            });
+        }
+    }
+
+    println!("Embossing text...");
+    buffer.composite_onto(thickness, depth, mode);
+}
+
+/// Rasterizes `overlay` (resized to the thickness grid's dimensions) into
+/// a coverage buffer using its luminance, and blends it into `thickness`.
+pub fn composite_overlay(thickness: &mut ThicknessGrid, overlay: &DynamicImage, depth: f32, mode: BlendMode) {
+    let grid_height = thickness.len();
+    let grid_width = thickness[0].len();
+
+    let resized = overlay.resize_exact(
+        grid_width as u32,
+        grid_height as u32,
+        FilterType::Lanczos3,
+    );
+
+    // The overlay covers the whole grid, so its top-left corner sits at
+    // the grid's top row (y = grid_height - 1, since grid rows count from
+    // the bottom).
+    let mut buffer = CoverageBuffer::new((0, grid_height.saturating_sub(1)), grid_width, grid_height);
+    for y in 0..grid_height as u32 {
+        for x in 0..grid_width as u32 {
+            let pixel = resized.get_pixel(x, y);
+            let luma = (pixel[0] as f32 * 0.299 + pixel[1] as f32 * 0.587 + pixel[2] as f32 * 0.114) / 255.0;
+            buffer.accumulate(x as i32, y as i32, luma);
+        }
+    }
+
+    println!("Compositing overlay...");
+    buffer.composite_onto(thickness, depth, mode);
+}